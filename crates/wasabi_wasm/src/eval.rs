@@ -0,0 +1,357 @@
+//! Concrete evaluation of unary and binary WebAssembly instructions on values, following the
+//! semantics of the specification (https://webassembly.github.io/spec/core/exec/numerics.html).
+//! Useful for, e.g., constant folding or interpreting code.
+
+use ordered_float::OrderedFloat;
+
+use crate::BinaryOp;
+use crate::UnaryOp;
+use crate::Val;
+
+impl Val {
+    /// Evaluate the unary instruction `op` on the operand `a`.
+    /// Returns `None` if the instruction traps (e.g., a float-to-int truncation of NaN or of an
+    /// out-of-range value), or if the type of `a` does not match the input type of `op`.
+    pub fn eval_unary(op: UnaryOp, a: Val) -> Option<Val> {
+        use UnaryOp::*;
+        use Val::*;
+        Some(match (op, a) {
+            (I32Eqz, I32(a)) => I32((a == 0) as i32),
+            (I64Eqz, I64(a)) => I32((a == 0) as i32),
+
+            (I32Clz, I32(a)) => I32(a.leading_zeros() as i32),
+            (I32Ctz, I32(a)) => I32(a.trailing_zeros() as i32),
+            (I32Popcnt, I32(a)) => I32(a.count_ones() as i32),
+
+            (I64Clz, I64(a)) => I64(a.leading_zeros() as i64),
+            (I64Ctz, I64(a)) => I64(a.trailing_zeros() as i64),
+            (I64Popcnt, I64(a)) => I64(a.count_ones() as i64),
+
+            // Rust's abs and neg only change the sign bit, as in WebAssembly, also for NaNs.
+            (F32Abs, F32(a)) => f32(a.abs()),
+            (F32Neg, F32(a)) => f32(-a.into_inner()),
+            (F32Ceil, F32(a)) => f32(a.ceil()),
+            (F32Floor, F32(a)) => f32(a.floor()),
+            (F32Trunc, F32(a)) => f32(a.trunc()),
+            (F32Nearest, F32(a)) => f32(a.round_ties_even()),
+            (F32Sqrt, F32(a)) => f32(a.sqrt()),
+
+            (F64Abs, F64(a)) => f64(a.abs()),
+            (F64Neg, F64(a)) => f64(-a.into_inner()),
+            (F64Ceil, F64(a)) => f64(a.ceil()),
+            (F64Floor, F64(a)) => f64(a.floor()),
+            (F64Trunc, F64(a)) => f64(a.trunc()),
+            (F64Nearest, F64(a)) => f64(a.round_ties_even()),
+            (F64Sqrt, F64(a)) => f64(a.sqrt()),
+
+            (I32WrapI64, I64(a)) => I32(a as i32),
+            // Converting f32 to f64 is exact, so check the range uniformly on f64.
+            (I32TruncF32S, F32(a)) => I32(trunc_in_range(a.into_inner() as f64, -2147483648.0, 2147483648.0)? as i32),
+            (I32TruncF32U, F32(a)) => I32(trunc_in_range(a.into_inner() as f64, 0.0, 4294967296.0)? as u32 as i32),
+            (I32TruncF64S, F64(a)) => I32(trunc_in_range(a.into_inner(), -2147483648.0, 2147483648.0)? as i32),
+            (I32TruncF64U, F64(a)) => I32(trunc_in_range(a.into_inner(), 0.0, 4294967296.0)? as u32 as i32),
+
+            (I64ExtendI32S, I32(a)) => I64(a as i64),
+            (I64ExtendI32U, I32(a)) => I64(a as u32 as i64),
+            (I64TruncF32S, F32(a)) => I64(trunc_in_range(a.into_inner() as f64, -9223372036854775808.0, 9223372036854775808.0)? as i64),
+            (I64TruncF32U, F32(a)) => I64(trunc_in_range(a.into_inner() as f64, 0.0, 18446744073709551616.0)? as u64 as i64),
+            (I64TruncF64S, F64(a)) => I64(trunc_in_range(a.into_inner(), -9223372036854775808.0, 9223372036854775808.0)? as i64),
+            (I64TruncF64U, F64(a)) => I64(trunc_in_range(a.into_inner(), 0.0, 18446744073709551616.0)? as u64 as i64),
+
+            // Rust's int-to-float casts round to nearest, ties to even, as in WebAssembly.
+            (F32ConvertI32S, I32(a)) => f32(a as f32),
+            (F32ConvertI32U, I32(a)) => f32(a as u32 as f32),
+            (F32ConvertI64S, I64(a)) => f32(a as f32),
+            (F32ConvertI64U, I64(a)) => f32(a as u64 as f32),
+            (F32DemoteF64, F64(a)) => f32(a.into_inner() as f32),
+
+            (F64ConvertI32S, I32(a)) => f64(a as f64),
+            (F64ConvertI32U, I32(a)) => f64(a as u32 as f64),
+            (F64ConvertI64S, I64(a)) => f64(a as f64),
+            (F64ConvertI64U, I64(a)) => f64(a as u64 as f64),
+            (F64PromoteF32, F32(a)) => f64(a.into_inner() as f64),
+
+            (I32ReinterpretF32, F32(a)) => I32(a.to_bits() as i32),
+            (I64ReinterpretF64, F64(a)) => I64(a.to_bits() as i64),
+            (F32ReinterpretI32, I32(a)) => f32(f32::from_bits(a as u32)),
+            (F64ReinterpretI64, I64(a)) => f64(f64::from_bits(a as u64)),
+
+            // Type mismatch between op and operand.
+            _ => return None,
+        })
+    }
+
+    /// Evaluate the binary instruction `op` on the operands `a` (first, i.e., lower on the stack)
+    /// and `b` (second, i.e., top of the stack).
+    /// Returns `None` if the instruction traps (integer division or remainder by zero, or signed
+    /// division overflow), or if the operand types do not match the input types of `op`.
+    pub fn eval_binary(op: BinaryOp, a: Val, b: Val) -> Option<Val> {
+        use BinaryOp::*;
+        use Val::*;
+        Some(match (op, a, b) {
+            (I32Eq, I32(a), I32(b)) => I32((a == b) as i32),
+            (I32Ne, I32(a), I32(b)) => I32((a != b) as i32),
+            (I32LtS, I32(a), I32(b)) => I32((a < b) as i32),
+            (I32LtU, I32(a), I32(b)) => I32(((a as u32) < (b as u32)) as i32),
+            (I32GtS, I32(a), I32(b)) => I32((a > b) as i32),
+            (I32GtU, I32(a), I32(b)) => I32((a as u32 > b as u32) as i32),
+            (I32LeS, I32(a), I32(b)) => I32((a <= b) as i32),
+            (I32LeU, I32(a), I32(b)) => I32((a as u32 <= b as u32) as i32),
+            (I32GeS, I32(a), I32(b)) => I32((a >= b) as i32),
+            (I32GeU, I32(a), I32(b)) => I32((a as u32 >= b as u32) as i32),
+
+            (I64Eq, I64(a), I64(b)) => I32((a == b) as i32),
+            (I64Ne, I64(a), I64(b)) => I32((a != b) as i32),
+            (I64LtS, I64(a), I64(b)) => I32((a < b) as i32),
+            (I64LtU, I64(a), I64(b)) => I32(((a as u64) < (b as u64)) as i32),
+            (I64GtS, I64(a), I64(b)) => I32((a > b) as i32),
+            (I64GtU, I64(a), I64(b)) => I32((a as u64 > b as u64) as i32),
+            (I64LeS, I64(a), I64(b)) => I32((a <= b) as i32),
+            (I64LeU, I64(a), I64(b)) => I32((a as u64 <= b as u64) as i32),
+            (I64GeS, I64(a), I64(b)) => I32((a >= b) as i32),
+            (I64GeU, I64(a), I64(b)) => I32((a as u64 >= b as u64) as i32),
+
+            // Compare the inner floats, not the OrderedFloats, which would consider NaN == NaN.
+            (F32Eq, F32(a), F32(b)) => I32((a.into_inner() == b.into_inner()) as i32),
+            (F32Ne, F32(a), F32(b)) => I32((a.into_inner() != b.into_inner()) as i32),
+            (F32Lt, F32(a), F32(b)) => I32((a.into_inner() < b.into_inner()) as i32),
+            (F32Gt, F32(a), F32(b)) => I32((a.into_inner() > b.into_inner()) as i32),
+            (F32Le, F32(a), F32(b)) => I32((a.into_inner() <= b.into_inner()) as i32),
+            (F32Ge, F32(a), F32(b)) => I32((a.into_inner() >= b.into_inner()) as i32),
+
+            (F64Eq, F64(a), F64(b)) => I32((a.into_inner() == b.into_inner()) as i32),
+            (F64Ne, F64(a), F64(b)) => I32((a.into_inner() != b.into_inner()) as i32),
+            (F64Lt, F64(a), F64(b)) => I32((a.into_inner() < b.into_inner()) as i32),
+            (F64Gt, F64(a), F64(b)) => I32((a.into_inner() > b.into_inner()) as i32),
+            (F64Le, F64(a), F64(b)) => I32((a.into_inner() <= b.into_inner()) as i32),
+            (F64Ge, F64(a), F64(b)) => I32((a.into_inner() >= b.into_inner()) as i32),
+
+            (I32Add, I32(a), I32(b)) => I32(a.wrapping_add(b)),
+            (I32Sub, I32(a), I32(b)) => I32(a.wrapping_sub(b)),
+            (I32Mul, I32(a), I32(b)) => I32(a.wrapping_mul(b)),
+            // checked_div returns None for both division by zero and i32::MIN / -1, which both trap.
+            (I32DivS, I32(a), I32(b)) => I32(a.checked_div(b)?),
+            (I32DivU, I32(a), I32(b)) => I32((a as u32).checked_div(b as u32)? as i32),
+            // Unlike division, i32::MIN % -1 does not trap but is 0.
+            (I32RemS, I32(_), I32(0)) => return None,
+            (I32RemS, I32(a), I32(b)) => I32(a.wrapping_rem(b)),
+            (I32RemU, I32(a), I32(b)) => I32((a as u32).checked_rem(b as u32)? as i32),
+            (I32And, I32(a), I32(b)) => I32(a & b),
+            (I32Or, I32(a), I32(b)) => I32(a | b),
+            (I32Xor, I32(a), I32(b)) => I32(a ^ b),
+            // wrapping_sh* and rotate_* take the shift count modulo the bit width, as in WebAssembly.
+            (I32Shl, I32(a), I32(b)) => I32(a.wrapping_shl(b as u32)),
+            (I32ShrS, I32(a), I32(b)) => I32(a.wrapping_shr(b as u32)),
+            (I32ShrU, I32(a), I32(b)) => I32((a as u32).wrapping_shr(b as u32) as i32),
+            (I32Rotl, I32(a), I32(b)) => I32(a.rotate_left(b as u32)),
+            (I32Rotr, I32(a), I32(b)) => I32(a.rotate_right(b as u32)),
+
+            (I64Add, I64(a), I64(b)) => I64(a.wrapping_add(b)),
+            (I64Sub, I64(a), I64(b)) => I64(a.wrapping_sub(b)),
+            (I64Mul, I64(a), I64(b)) => I64(a.wrapping_mul(b)),
+            (I64DivS, I64(a), I64(b)) => I64(a.checked_div(b)?),
+            (I64DivU, I64(a), I64(b)) => I64((a as u64).checked_div(b as u64)? as i64),
+            (I64RemS, I64(_), I64(0)) => return None,
+            (I64RemS, I64(a), I64(b)) => I64(a.wrapping_rem(b)),
+            (I64RemU, I64(a), I64(b)) => I64((a as u64).checked_rem(b as u64)? as i64),
+            (I64And, I64(a), I64(b)) => I64(a & b),
+            (I64Or, I64(a), I64(b)) => I64(a | b),
+            (I64Xor, I64(a), I64(b)) => I64(a ^ b),
+            (I64Shl, I64(a), I64(b)) => I64(a.wrapping_shl(b as u32)),
+            (I64ShrS, I64(a), I64(b)) => I64(a.wrapping_shr(b as u32)),
+            (I64ShrU, I64(a), I64(b)) => I64((a as u64).wrapping_shr(b as u32) as i64),
+            (I64Rotl, I64(a), I64(b)) => I64(a.rotate_left(b as u32)),
+            (I64Rotr, I64(a), I64(b)) => I64(a.rotate_right(b as u32)),
+
+            (F32Add, F32(a), F32(b)) => f32(a.into_inner() + b.into_inner()),
+            (F32Sub, F32(a), F32(b)) => f32(a.into_inner() - b.into_inner()),
+            (F32Mul, F32(a), F32(b)) => f32(a.into_inner() * b.into_inner()),
+            (F32Div, F32(a), F32(b)) => f32(a.into_inner() / b.into_inner()),
+            (F32Min, F32(a), F32(b)) => f32(f32_min(a.into_inner(), b.into_inner())),
+            (F32Max, F32(a), F32(b)) => f32(f32_max(a.into_inner(), b.into_inner())),
+            (F32Copysign, F32(a), F32(b)) => f32(a.copysign(b.into_inner())),
+
+            (F64Add, F64(a), F64(b)) => f64(a.into_inner() + b.into_inner()),
+            (F64Sub, F64(a), F64(b)) => f64(a.into_inner() - b.into_inner()),
+            (F64Mul, F64(a), F64(b)) => f64(a.into_inner() * b.into_inner()),
+            (F64Div, F64(a), F64(b)) => f64(a.into_inner() / b.into_inner()),
+            (F64Min, F64(a), F64(b)) => f64(f64_min(a.into_inner(), b.into_inner())),
+            (F64Max, F64(a), F64(b)) => f64(f64_max(a.into_inner(), b.into_inner())),
+            (F64Copysign, F64(a), F64(b)) => f64(a.copysign(b.into_inner())),
+
+            // Type mismatch between op and operands.
+            _ => return None,
+        })
+    }
+}
+
+fn f32(f: f32) -> Val {
+    Val::F32(OrderedFloat(f))
+}
+
+fn f64(f: f64) -> Val {
+    Val::F64(OrderedFloat(f))
+}
+
+/// Truncate `f` towards zero and return it, if the result is in `[min, max_exclusive)`.
+/// Returns `None` for NaN and infinities, for which the WebAssembly truncations trap.
+fn trunc_in_range(f: f64, min: f64, max_exclusive: f64) -> Option<f64> {
+    let truncated = f.trunc();
+    (truncated >= min && truncated < max_exclusive).then_some(truncated)
+}
+
+// Unlike Rust's f32::min/max etc., WebAssembly's min/max propagate NaNs and order -0.0 < +0.0.
+
+fn f32_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_negative() { a } else { b }
+    } else {
+        a.min(b)
+    }
+}
+
+fn f32_max(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_positive() { a } else { b }
+    } else {
+        a.max(b)
+    }
+}
+
+fn f64_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_negative() { a } else { b }
+    } else {
+        a.min(b)
+    }
+}
+
+fn f64_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_positive() { a } else { b }
+    } else {
+        a.max(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BinaryOp::*;
+    use crate::UnaryOp::*;
+    use crate::Val;
+    use crate::Val::*;
+
+    fn f32(f: f32) -> Val {
+        F32(f.into())
+    }
+
+    fn f64(f: f64) -> Val {
+        F64(f.into())
+    }
+
+    #[test]
+    pub fn integer_arithmetic_wraps() {
+        assert_eq!(Val::eval_binary(I32Add, I32(i32::MAX), I32(1)), Some(I32(i32::MIN)));
+        assert_eq!(Val::eval_binary(I32Sub, I32(i32::MIN), I32(1)), Some(I32(i32::MAX)));
+        assert_eq!(Val::eval_binary(I64Mul, I64(i64::MAX), I64(2)), Some(I64(-2)));
+    }
+
+    #[test]
+    pub fn signed_and_unsigned_division() {
+        assert_eq!(Val::eval_binary(I32DivS, I32(-7), I32(2)), Some(I32(-3)));
+        assert_eq!(Val::eval_binary(I32DivU, I32(-7), I32(2)), Some(I32(0x7fff_fffc)));
+        assert_eq!(Val::eval_binary(I32RemS, I32(-7), I32(2)), Some(I32(-1)));
+        assert_eq!(Val::eval_binary(I32RemU, I32(-7), I32(2)), Some(I32(1)));
+        assert_eq!(Val::eval_binary(I64DivU, I64(-1), I64(2)), Some(I64(i64::MAX)));
+    }
+
+    #[test]
+    pub fn division_traps() {
+        assert_eq!(Val::eval_binary(I32DivS, I32(1), I32(0)), None);
+        assert_eq!(Val::eval_binary(I32DivU, I32(1), I32(0)), None);
+        assert_eq!(Val::eval_binary(I64RemS, I64(1), I64(0)), None);
+        assert_eq!(Val::eval_binary(I64RemU, I64(1), I64(0)), None);
+        // Signed overflow traps for division, but not for remainder.
+        assert_eq!(Val::eval_binary(I32DivS, I32(i32::MIN), I32(-1)), None);
+        assert_eq!(Val::eval_binary(I64DivS, I64(i64::MIN), I64(-1)), None);
+        assert_eq!(Val::eval_binary(I32RemS, I32(i32::MIN), I32(-1)), Some(I32(0)));
+        assert_eq!(Val::eval_binary(I64RemS, I64(i64::MIN), I64(-1)), Some(I64(0)));
+    }
+
+    #[test]
+    pub fn shift_counts_are_masked() {
+        assert_eq!(Val::eval_binary(I32Shl, I32(1), I32(33)), Some(I32(2)));
+        assert_eq!(Val::eval_binary(I32ShrS, I32(-8), I32(-31)), Some(I32(-4)));
+        assert_eq!(Val::eval_binary(I32ShrU, I32(-8), I32(32)), Some(I32(-8)));
+        assert_eq!(Val::eval_binary(I64Shl, I64(1), I64(65)), Some(I64(2)));
+        assert_eq!(Val::eval_binary(I64ShrU, I64(-1), I64(127)), Some(I64(1)));
+        assert_eq!(Val::eval_binary(I32Rotl, I32(i32::MIN), I32(33)), Some(I32(1)));
+        assert_eq!(Val::eval_binary(I64Rotr, I64(1), I64(-1)), Some(I64(2)));
+    }
+
+    #[test]
+    pub fn float_nan_propagation() {
+        for op in [F32Add, F32Sub, F32Mul, F32Div, F32Min, F32Max] {
+            match Val::eval_binary(op, f32(1.0), f32(f32::NAN)) {
+                Some(F32(f)) => assert!(f.is_nan(), "{op} should propagate NaN"),
+                result => panic!("unexpected result {result:?} for {op}"),
+            }
+        }
+        match Val::eval_binary(F64Min, f64(f64::NAN), f64(f64::NEG_INFINITY)) {
+            Some(F64(f)) => assert!(f.is_nan()),
+            result => panic!("unexpected result {result:?}"),
+        }
+        // NaN is unequal to everything, including itself.
+        assert_eq!(Val::eval_binary(F32Eq, f32(f32::NAN), f32(f32::NAN)), Some(I32(0)));
+        assert_eq!(Val::eval_binary(F64Ne, f64(f64::NAN), f64(f64::NAN)), Some(I32(1)));
+    }
+
+    #[test]
+    pub fn float_min_max_order_zeros() {
+        let min = Val::eval_binary(F32Min, f32(0.0), f32(-0.0)).unwrap();
+        assert_eq!(min.to_string(), "-0");
+        let max = Val::eval_binary(F64Max, f64(-0.0), f64(0.0)).unwrap();
+        assert_eq!(max.to_string(), "0");
+    }
+
+    #[test]
+    pub fn float_to_int_truncation() {
+        assert_eq!(Val::eval_unary(I32TruncF32S, f32(-1.9)), Some(I32(-1)));
+        assert_eq!(Val::eval_unary(I32TruncF64U, f64(4294967295.5)), Some(I32(-1)));
+        assert_eq!(Val::eval_unary(I32TruncF64U, f64(-0.9)), Some(I32(0)));
+        assert_eq!(Val::eval_unary(I32TruncF64U, f64(4294967296.0)), None);
+        assert_eq!(Val::eval_unary(I32TruncF64S, f64(-2147483649.0)), None);
+        assert_eq!(Val::eval_unary(I64TruncF32S, f32(f32::NAN)), None);
+        assert_eq!(Val::eval_unary(I64TruncF64U, f64(f64::INFINITY)), None);
+    }
+
+    #[test]
+    pub fn unary_integer_ops() {
+        assert_eq!(Val::eval_unary(I32Eqz, I32(0)), Some(I32(1)));
+        assert_eq!(Val::eval_unary(I64Eqz, I64(5)), Some(I32(0)));
+        assert_eq!(Val::eval_unary(I32Clz, I32(1)), Some(I32(31)));
+        assert_eq!(Val::eval_unary(I64Ctz, I64(0)), Some(I64(64)));
+        assert_eq!(Val::eval_unary(I64ExtendI32U, I32(-1)), Some(I64(0xffff_ffff)));
+        assert_eq!(Val::eval_unary(I32WrapI64, I64(0x1_0000_0002)), Some(I32(2)));
+    }
+
+    #[test]
+    pub fn nearest_rounds_ties_to_even() {
+        assert_eq!(Val::eval_unary(F32Nearest, f32(2.5)), Some(f32(2.0)));
+        assert_eq!(Val::eval_unary(F64Nearest, f64(-3.5)), Some(f64(-4.0)));
+    }
+
+    #[test]
+    pub fn type_mismatch_is_none() {
+        assert_eq!(Val::eval_unary(I32Eqz, I64(0)), None);
+        assert_eq!(Val::eval_binary(I32Add, I32(1), I64(1)), None);
+    }
+}
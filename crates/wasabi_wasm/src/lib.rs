@@ -10,6 +10,7 @@ pub use crate::error::*;
 pub mod types;
 
 mod encode;
+mod eval;
 mod extensions;
 mod parse;
 